@@ -19,12 +19,27 @@
 //!
 //! - Package name.
 //!
-//! - Metadata defined in `Cargo.toml`.
+//! - Metadata defined in `Cargo.toml`, with `[workspace.metadata.inwelling.*]` (if any)
+//! deep-merged in underneath `[package.metadata.inwelling.*]` -- see [`Package::metadata`].
+//! Use [`Package::deserialize_metadata`] or [`Downstream::deserialize_all`] to deserialize it
+//! into a caller-provided type instead of hand-walking the raw [`toml::Value`].
 //!
 //! - Manifest paths of `Cargo.toml`.
 //!
 //! - Source file paths(optional). Call `collect_downstream()` with the argument
-//! `inwelling::Opt::dump_rs_paths == true` to collect.
+//! `inwelling::Opts::dump_rs_paths == true` to collect, optionally narrowed by
+//! `inwelling::Opts::scan_dirs`/`include`/`exclude`.
+//!
+//! - Enabled feature flags(optional). Call `collect_downstream()` with the argument
+//! `inwelling::Opts::dump_features == true` to collect.
+//!
+//! Discovery defaults to polling the `build/*/out/manifest_dir.inwelling` files written by
+//! `to()`, but `inwelling::Opts::discovery == inwelling::Discovery::CargoMetadata` switches to
+//! walking `cargo metadata`'s resolve graph instead, which doesn't require downstream crates'
+//! `build.rs` to have already run.
+//!
+//! `collect_downstream()` panics on failure; [`try_collect_downstream()`] returns an
+//! [`InwellingError`] instead, for callers that want to handle failures themselves.
 //!
 //! # Quickstart
 //!
@@ -53,6 +68,7 @@ use std::{
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
+    process::Command,
     thread,
     time::Duration,
 };
@@ -86,11 +102,170 @@ pub struct Package {
     pub name     : String,
     /// path of `Cargo.toml`.
     pub manifest : PathBuf,
-    /// metadata represented in Toml.
+    /// metadata represented in Toml. If this package's manifest is part of a workspace that
+    /// declares `[workspace.metadata.inwelling.*]`, that table is deep-merged in underneath the
+    /// package's own `[package.metadata.inwelling.*]` (package-level keys win on conflict) --
+    /// including the case where the package declares no table of its own and this field is
+    /// simply the inherited workspace table.
     pub metadata : toml::Value,
+    /// the `[workspace.metadata.inwelling.*]` table inherited from the owning workspace root,
+    /// before being merged into `metadata`, or `None` if this package isn't in a workspace, or
+    /// its workspace root declares no such table.
+    pub workspace_metadata : Option<toml::Value>,
     /// .rs files under src/, examples/ and tests/ directories if `dump_rs_file`
     /// is true, otherwise `None`.
     pub rs_paths : Option<Vec<PathBuf>>,
+    /// feature flags enabled on this downstream crate for the current build, if
+    /// `Opts::dump_features` is true, otherwise empty.
+    pub features : Vec<String>,
+}
+
+impl Package {
+    /// Deserializes `metadata` into a caller-provided type, instead of hand-walking the raw
+    /// `toml::Value`. Returns a [`MetadataError`] carrying this package's manifest path if `T`
+    /// doesn't match the shape of the collected metadata.
+    pub fn deserialize_metadata<T: serde::de::DeserializeOwned>( &self ) -> Result<T, MetadataError> {
+        T::deserialize( self.metadata.clone() )
+            .map_err( |source| MetadataError{ manifest: self.manifest.clone(), source })
+    }
+}
+
+impl Downstream {
+    /// Deserializes every package's metadata into `T`, keyed by package name. Fails on the
+    /// first package whose metadata doesn't match `T`'s shape.
+    pub fn deserialize_all<T: serde::de::DeserializeOwned>( &self ) -> Result<Vec<(String, T)>, MetadataError> {
+        self.packages.iter()
+            .map( |package| package.deserialize_metadata::<T>().map( |value| ( package.name.clone(), value )))
+            .collect()
+    }
+}
+
+/// Error returned when a [`Package`]'s metadata fails to deserialize into a caller-provided type.
+#[derive( Debug )]
+pub struct MetadataError {
+    /// path of the `Cargo.toml` whose metadata could not be deserialized.
+    pub manifest : PathBuf,
+    /// the underlying deserialization error.
+    pub source   : toml::de::Error,
+}
+
+impl std::fmt::Display for MetadataError {
+    fn fmt( &self, f: &mut std::fmt::Formatter ) -> std::fmt::Result {
+        write!( f, "failed to deserialize metadata in {:?}: {}", self.manifest, self.source )
+    }
+}
+
+impl std::error::Error for MetadataError {
+    fn source( &self ) -> Option<&(dyn std::error::Error + 'static)> {
+        Some( &self.source )
+    }
+}
+
+/// Errors that can occur while discovering downstream crates or reading their manifests, as
+/// returned by [`try_collect_downstream()`]. Variants tied to a particular file or directory
+/// carry its path, telling the caller which downstream crate caused the problem; variants
+/// about the build environment or the `cargo metadata` run as a whole carry no path.
+#[derive( Debug )]
+pub enum InwellingError {
+    /// `$OUT_DIR` isn't set; `collect_downstream()` must run from within `build.rs`.
+    MissingOutDir,
+    /// `$CARGO_PKG_NAME` isn't set; `collect_downstream()` must run from within `build.rs`.
+    MissingPkgName,
+    /// `$CARGO_MANIFEST_DIR` isn't set; `collect_downstream()` must run from within `build.rs`.
+    MissingManifestDir,
+    /// `$OUT_DIR` didn't have a `build` directory among its ancestors.
+    MissingBuildDir{ out_dir: PathBuf },
+    /// the `build` directory couldn't be listed.
+    UnreadableBuildDir{ build_dir: PathBuf, source: std::io::Error },
+    /// an entry under the `build` directory couldn't be walked while polling for other
+    /// in-progress builds.
+    UnwalkableBuildDir{ build_dir: PathBuf, source: walkdir::Error },
+    /// a `manifest_dir.inwelling` marker file written by `to()` couldn't be read.
+    UnreadableManifestMarker{ path: PathBuf, source: std::io::Error },
+    /// a `manifest_dir.inwelling` marker file was empty.
+    MalformedManifestMarker{ path: PathBuf },
+    /// a downstream `Cargo.toml` couldn't be read.
+    UnreadableManifest{ manifest: PathBuf, source: std::io::Error },
+    /// a downstream `Cargo.toml` wasn't valid TOML.
+    MalformedManifest{ manifest: PathBuf, source: toml::de::Error },
+    /// a downstream `Cargo.toml` has no `[package]` section.
+    MissingPackageSection{ manifest: PathBuf },
+    /// a downstream `Cargo.toml`'s `[package]` section has no string `name`.
+    MissingPackageName{ manifest: PathBuf },
+    /// a downstream crate's metadata, as reported by `cargo metadata`, couldn't be converted
+    /// from JSON back into `toml::Value`.
+    MalformedMetadata{ manifest: PathBuf, source: toml::ser::Error },
+    /// `cargo metadata` couldn't be run.
+    CargoMetadataFailed{ source: std::io::Error },
+    /// `cargo metadata` ran but exited with a non-zero status, e.g. because some manifest in
+    /// the workspace has an unresolvable dependency.
+    CargoMetadataExitedWithError{ status: std::process::ExitStatus, stderr: String },
+    /// `cargo metadata`'s stdout wasn't valid JSON.
+    MalformedCargoMetadataOutput{ source: serde_json::Error },
+    /// `CARGO_PKG_NAME` didn't appear in `cargo metadata`'s `packages[]`.
+    PackageNotInCargoMetadata{ name: String },
+    /// an `Opts::include`/`Opts::exclude` entry wasn't a valid glob pattern.
+    InvalidGlobPattern{ pattern: String, source: glob::PatternError },
+}
+
+impl std::fmt::Display for InwellingError {
+    fn fmt( &self, f: &mut std::fmt::Formatter ) -> std::fmt::Result {
+        match self {
+            InwellingError::MissingOutDir =>
+                write!( f, "$OUT_DIR should exist; run this from build.rs" ),
+            InwellingError::MissingPkgName =>
+                write!( f, "$CARGO_PKG_NAME should exist; run this from build.rs" ),
+            InwellingError::MissingManifestDir =>
+                write!( f, "$CARGO_MANIFEST_DIR should exist; run this from build.rs" ),
+            InwellingError::MissingBuildDir{ out_dir } =>
+                write!( f, "{out_dir:?} should have a 'build' directory among its ancestors" ),
+            InwellingError::UnreadableBuildDir{ build_dir, source } =>
+                write!( f, "failed to list {build_dir:?}: {source}" ),
+            InwellingError::UnwalkableBuildDir{ build_dir, source } =>
+                write!( f, "failed to walk {build_dir:?} while waiting for other builds: {source}" ),
+            InwellingError::UnreadableManifestMarker{ path, source } =>
+                write!( f, "failed to read {path:?}: {source}" ),
+            InwellingError::MalformedManifestMarker{ path } =>
+                write!( f, "{path:?} should contain the line of manifest dir" ),
+            InwellingError::UnreadableManifest{ manifest, source } =>
+                write!( f, "failed to read {manifest:?}: {source}" ),
+            InwellingError::MalformedManifest{ manifest, source } =>
+                write!( f, "{manifest:?} should be a valid manifest: {source}" ),
+            InwellingError::MissingPackageSection{ manifest } =>
+                write!( f, "{manifest:?} should contain '[package]' section" ),
+            InwellingError::MissingPackageName{ manifest } =>
+                write!( f, "{manifest:?} should contain a string package name" ),
+            InwellingError::MalformedMetadata{ manifest, source } =>
+                write!( f, "{manifest:?}'s metadata should convert from JSON to TOML: {source}" ),
+            InwellingError::CargoMetadataFailed{ source } =>
+                write!( f, "`cargo metadata` should run successfully: {source}" ),
+            InwellingError::CargoMetadataExitedWithError{ status, stderr } =>
+                write!( f, "`cargo metadata` exited with {status}: {stderr}" ),
+            InwellingError::MalformedCargoMetadataOutput{ source } =>
+                write!( f, "`cargo metadata`'s stdout should be valid JSON: {source}" ),
+            InwellingError::PackageNotInCargoMetadata{ name } =>
+                write!( f, "{name:?} should appear in `cargo metadata`'s packages[]" ),
+            InwellingError::InvalidGlobPattern{ pattern, source } =>
+                write!( f, "{pattern:?} should be a valid glob pattern: {source}" ),
+        }
+    }
+}
+
+impl std::error::Error for InwellingError {
+    fn source( &self ) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InwellingError::UnreadableBuildDir{ source, .. }        => Some( source ),
+            InwellingError::UnwalkableBuildDir{ source, .. }        => Some( source ),
+            InwellingError::UnreadableManifestMarker{ source, .. }  => Some( source ),
+            InwellingError::UnreadableManifest{ source, .. }        => Some( source ),
+            InwellingError::MalformedManifest{ source, .. }         => Some( source ),
+            InwellingError::MalformedMetadata{ source, .. }         => Some( source ),
+            InwellingError::CargoMetadataFailed{ source, .. }       => Some( source ),
+            InwellingError::MalformedCargoMetadataOutput{ source, .. } => Some( source ),
+            InwellingError::InvalidGlobPattern{ source, .. }         => Some( source ),
+            _ => None,
+        }
+    }
 }
 
 fn scan_rs_paths( current_dir: impl AsRef<Path>, rs_paths: &mut Vec<PathBuf> ) {
@@ -110,7 +285,123 @@ fn scan_rs_paths( current_dir: impl AsRef<Path>, rs_paths: &mut Vec<PathBuf> ) {
     }
 }
 
+/// Compiles glob patterns from `Opts::include`/`Opts::exclude`, as `glob::Pattern` does for
+/// cargo's own packaging code.
+fn try_compile_patterns( patterns: &[String] ) -> Result<Vec<glob::Pattern>, InwellingError> {
+    patterns.iter()
+        .map( |pattern| glob::Pattern::new( pattern )
+            .map_err( |source| InwellingError::InvalidGlobPattern{ pattern: pattern.clone(), source }))
+        .collect()
+}
+
+/// Scans `scan_dirs` (relative to `manifest_dir`) for `.rs` files, keeping only the ones
+/// matched by `include` (or everything, if `include` is empty) and not matched by `exclude`.
+fn scan_filtered_rs_paths(
+    manifest_dir : &Path,
+    scan_dirs    : &[PathBuf],
+    include      : &[glob::Pattern],
+    exclude      : &[glob::Pattern],
+) -> Vec<PathBuf> {
+    let mut rs_paths = Vec::new();
+    for scan_dir in scan_dirs {
+        scan_rs_paths( &manifest_dir.join( scan_dir ), &mut rs_paths );
+    }
+
+    rs_paths.retain( |path| {
+        let relative = path.strip_prefix( manifest_dir ).unwrap_or( path );
+        let included = include.is_empty() || include.iter().any( |pattern| pattern.matches_path( relative ));
+        let excluded = exclude.iter().any( |pattern| pattern.matches_path( relative ));
+        included && !excluded
+    });
+
+    rs_paths
+}
+
+/// Walks `manifest_path`'s ancestor directories looking for the `Cargo.toml` that declares the
+/// owning `[workspace]` table, the same layering cargo itself performs when elaborating a
+/// manifest. Returns `None` if no such manifest is found (e.g. `manifest_path` isn't part of a
+/// workspace).
+fn find_workspace_root( manifest_path: &Path ) -> Option<PathBuf> {
+    let mut dir = Some( manifest_path.parent()? );
+    while let Some( current_dir ) = dir {
+        let candidate = current_dir.join( "Cargo.toml" );
+        if let Ok( contents ) = fs::read_to_string( &candidate ) {
+            if let Ok( table ) = contents.parse::<toml::Table>() {
+                if table.contains_key( "workspace" ) {
+                    return Some( candidate );
+                }
+            }
+        }
+        dir = current_dir.parent();
+    }
+    None
+}
+
+/// Reads `[workspace.metadata.inwelling.<build_name>]` from the workspace root manifest, if any.
+fn workspace_inwelling_metadata( workspace_root: &Path, build_name: &str ) -> Option<toml::Value> {
+    let contents = fs::read_to_string( workspace_root ).ok()?;
+    let table = contents.parse::<toml::Table>().ok()?;
+    table.get( "workspace" )?.get( "metadata" )?.get( "inwelling" )?.get( build_name ).cloned()
+}
+
+/// Deep-merges `overlay` into `base`: table keys present in both are merged recursively, and
+/// `overlay`'s value wins on conflict. Used to merge a package's own
+/// `[package.metadata.inwelling.*]` table (`overlay`) over the workspace-inherited one (`base`).
+fn deep_merge( base: toml::Value, overlay: toml::Value ) -> toml::Value {
+    match ( base, overlay ) {
+        ( toml::Value::Table( mut base_table ), toml::Value::Table( overlay_table )) => {
+            for ( key, overlay_value ) in overlay_table {
+                let merged_value = match base_table.remove( &key ) {
+                    Some( base_value ) => deep_merge( base_value, overlay_value ),
+                    None => overlay_value,
+                };
+                base_table.insert( key, merged_value );
+            }
+            toml::Value::Table( base_table )
+        },
+        ( _, overlay ) => overlay,
+    }
+}
+
+/// Resolves the final `Package::metadata`/`Package::workspace_metadata` pair for one downstream
+/// manifest, deep-merging `[workspace.metadata.inwelling.<build_name>]` underneath the
+/// package's own `[package.metadata.inwelling.<build_name>]`. Returns `None` if neither table
+/// is present, meaning this manifest isn't a downstream package of `build_name` at all -- a
+/// member relying solely on workspace-level metadata (with no `[package.metadata.inwelling.*]`
+/// of its own) still resolves to `Some`.
+fn resolve_metadata( manifest_path: &Path, build_name: &str, package_metadata: Option<toml::Value> ) -> Option<( toml::Value, Option<toml::Value> )> {
+    let workspace_metadata = find_workspace_root( manifest_path )
+        .and_then( |workspace_root| workspace_inwelling_metadata( &workspace_root, build_name ));
+
+    match ( workspace_metadata, package_metadata ) {
+        ( None, None ) => None,
+        ( None, Some( package_metadata )) => Some(( package_metadata, None )),
+        ( Some( workspace_metadata ), None ) => Some(( workspace_metadata.clone(), Some( workspace_metadata ))),
+        ( Some( workspace_metadata ), Some( package_metadata )) =>
+            Some(( deep_merge( workspace_metadata.clone(), package_metadata ), Some( workspace_metadata ))),
+    }
+}
+
+/// The backend used to discover downstream crates.
+#[derive( Clone, Copy, Debug, PartialEq, Eq )]
+pub enum Discovery {
+    /// Scan the `build/*/out/manifest_dir.inwelling` files written by downstream crates'
+    /// `inwelling::to()` calls, waiting in 5-second increments until all sibling build
+    /// scripts appear to have finished. This is the original behavior, kept as the default
+    /// for backward compatibility.
+    BuildDirScan,
+    /// Run `cargo metadata` and walk the resolve graph to find every workspace member that
+    /// transitively depends on `CARGO_PKG_NAME`. This is deterministic and does not wait on
+    /// sibling build scripts, but requires downstream crates to be workspace members.
+    CargoMetadata,
+}
+
+impl Default for Discovery {
+    fn default() -> Discovery { Discovery::BuildDirScan }
+}
+
 /// Options passed to inwelling().
+#[derive( Clone )]
 pub struct Opts {
     /// build.rs using inwelling() will re-run if downstream crates' Cargo.toml files have been changed.
     pub watch_manifest : bool,
@@ -118,6 +409,20 @@ pub struct Opts {
     pub watch_rs_files : bool,
     /// if this flag is true, inwelling()'s returning value will contain .rs file paths.
     pub dump_rs_paths  : bool,
+    /// the backend used to discover downstream crates.
+    pub discovery      : Discovery,
+    /// if this flag is true, inwelling()'s returning value will contain the feature flags
+    /// enabled on each downstream crate, obtained via `cargo metadata`'s resolve graph.
+    pub dump_features  : bool,
+    /// directories scanned for `.rs` files, relative to each downstream manifest's directory.
+    /// Defaults to `src/`, `examples/` and `tests/`.
+    pub scan_dirs      : Vec<PathBuf>,
+    /// glob patterns, relative to each downstream manifest's directory, that a `.rs` path
+    /// must match to be kept. An empty list means "match everything".
+    pub include        : Vec<String>,
+    /// glob patterns, relative to each downstream manifest's directory, that a `.rs` path
+    /// must not match to be kept. Checked after `include`.
+    pub exclude        : Vec<String>,
 }
 
 impl Default for Opts {
@@ -126,6 +431,11 @@ impl Default for Opts {
             watch_manifest : true,
             watch_rs_files : false,
             dump_rs_paths  : false,
+            discovery      : Discovery::default(),
+            dump_features  : false,
+            scan_dirs      : vec![ PathBuf::from("src"), PathBuf::from("examples"), PathBuf::from("tests") ],
+            include        : Vec::new(),
+            exclude        : Vec::new(),
         }
     }
 }
@@ -139,72 +449,108 @@ impl Default for Opts {
 /// - metadata from `[package.metadata.inwelling.*]` sections in Cargo.toml files.
 ///
 /// - Optional .rs file paths.
-pub fn collect_downstream( Opts{ watch_manifest, watch_rs_files, dump_rs_paths }: Opts ) -> Downstream {
-    let build_name = env::var("CARGO_PKG_NAME").expect("CARGO_PKG_NAME");
-
-    let manifest_paths = locate_manifest_paths();
-
-    manifest_paths.into_iter().fold( Downstream::default(), |mut inwelling, (manifest_path, upstreams)| {
-        if upstreams.contains( &build_name ) {
-            let cargo_toml =
-                fs::read_to_string( PathBuf::from( &manifest_path ))
-                .expect( &format!( "to read {:?}", manifest_path ))
-                .parse::<toml::Table>()
-                .expect( &format!( "{:?} should be a valid manifest", manifest_path ));
-            let package = cargo_toml.get( "package" )
-                .expect( &format!( "{:?} should contain '[package]' section", manifest_path ));
-            let package_name = package.as_table()
-                .expect( &format!( "[package] section in {:?} should contain key-value pair(s)", manifest_path ))
-                .get( "name" )
-                .expect( &format!( "{:?} should contain package name", manifest_path ))
-                .as_str()
-                .expect( &format!( "{:?}'s package name should be a string", manifest_path ))
-                .to_owned();
+///
+/// # Panics
+///
+/// Panics on any [`InwellingError`]. Use [`try_collect_downstream()`] to handle failures, e.g.
+/// a partially-parseable workspace, without aborting the build.
+pub fn collect_downstream( opts: Opts ) -> Downstream {
+    try_collect_downstream( opts ).unwrap_or_else( |error| panic!( "{error}" ))
+}
 
-            let mut rs_paths = Vec::new();
+/// Fallible version of [`collect_downstream()`]. Missing environment variables, unreadable or
+/// malformed manifests and marker files, invalid glob patterns and a failed `cargo metadata`
+/// run are reported as an [`InwellingError`] instead of panicking. `cargo metadata` JSON that
+/// lacks an expected field (e.g. `packages[]`, `resolve.nodes[]`, a package `id`) still panics.
+pub fn try_collect_downstream( opts: Opts ) -> Result<Downstream, InwellingError> {
+    let build_name = env::var( "CARGO_PKG_NAME" ).map_err( |_| InwellingError::MissingPkgName )?;
 
-            if watch_manifest {
-                println!( "cargo:rerun-if-changed={}", manifest_path.to_str().unwrap() );
-            }
-            if dump_rs_paths || watch_rs_files {
-                let manifest_dir = manifest_path.parent().unwrap();
-                scan_rs_paths( &manifest_dir.join( "src"      ), &mut rs_paths );
-                scan_rs_paths( &manifest_dir.join( "examples" ), &mut rs_paths );
-                scan_rs_paths( &manifest_dir.join( "tests"    ), &mut rs_paths );
-                if watch_rs_files {
-                    rs_paths.iter().for_each( |rs_file|
-                        println!( "cargo:rerun-if-changed={}", rs_file.to_str().unwrap() ));
-                }
-            }
-            if let Some( metadata ) = package.get( "metadata" ) {
-                if let Some( metadata_inwelling ) = metadata.get("inwelling") {
-                    if let Some( metadata_inwelling_build ) = metadata_inwelling.get( &build_name ) {
-                        inwelling.packages.push( Package{
-                            name     : package_name,
-                            manifest : manifest_path,
-                            metadata : metadata_inwelling_build.clone(),
-                            rs_paths : if dump_rs_paths { Some( rs_paths )} else { None },
-                        });
-                    }
-                }
+    let dump_features_enabled = opts.dump_features;
+
+    let ( mut downstream, cargo_metadata ) = match opts.discovery {
+        Discovery::BuildDirScan  => ( try_collect_downstream_via_build_dir_scan( &build_name, opts )?, None ),
+        Discovery::CargoMetadata => {
+            let ( downstream, full_metadata ) = try_collect_downstream_via_cargo_metadata( &build_name, opts )?;
+            ( downstream, Some( full_metadata ))
+        },
+    };
+
+    if dump_features_enabled {
+        dump_features( &mut downstream, cargo_metadata )?;
+    }
+
+    Ok( downstream )
+}
+
+fn try_collect_downstream_via_build_dir_scan( build_name: &str, Opts{ watch_manifest, watch_rs_files, dump_rs_paths, scan_dirs, include, exclude, .. }: Opts ) -> Result<Downstream, InwellingError> {
+    let include = try_compile_patterns( &include )?;
+    let exclude = try_compile_patterns( &exclude )?;
+
+    let manifest_paths = locate_manifest_paths()?;
+
+    let mut inwelling = Downstream::default();
+
+    for ( manifest_path, upstreams ) in manifest_paths {
+        if !upstreams.iter().any( |upstream| upstream == build_name ) {
+            continue;
+        }
+
+        let cargo_toml = fs::read_to_string( &manifest_path )
+            .map_err( |source| InwellingError::UnreadableManifest{ manifest: manifest_path.clone(), source })?
+            .parse::<toml::Table>()
+            .map_err( |source| InwellingError::MalformedManifest{ manifest: manifest_path.clone(), source })?;
+        let package = cargo_toml.get( "package" )
+            .ok_or_else( || InwellingError::MissingPackageSection{ manifest: manifest_path.clone() })?;
+        let package_name = package.get( "name" )
+            .and_then( toml::Value::as_str )
+            .ok_or_else( || InwellingError::MissingPackageName{ manifest: manifest_path.clone() })?
+            .to_owned();
+
+        let mut rs_paths = Vec::new();
+
+        if watch_manifest {
+            println!( "cargo:rerun-if-changed={}", manifest_path.to_str().unwrap() );
+        }
+        if dump_rs_paths || watch_rs_files {
+            let manifest_dir = manifest_path.parent().unwrap();
+            rs_paths = scan_filtered_rs_paths( manifest_dir, &scan_dirs, &include, &exclude );
+            if watch_rs_files {
+                rs_paths.iter().for_each( |rs_file|
+                    println!( "cargo:rerun-if-changed={}", rs_file.to_str().unwrap() ));
             }
         }
+        let package_metadata = package.get( "metadata" )
+            .and_then( |metadata| metadata.get( "inwelling" ))
+            .and_then( |metadata_inwelling| metadata_inwelling.get( build_name ))
+            .cloned();
 
-        inwelling
-    })
+        if let Some(( metadata, workspace_metadata )) = resolve_metadata( &manifest_path, build_name, package_metadata ) {
+            inwelling.packages.push( Package{
+                name     : package_name,
+                manifest : manifest_path,
+                metadata,
+                workspace_metadata,
+                rs_paths : if dump_rs_paths { Some( rs_paths )} else { None },
+                features : Vec::new(),
+            });
+        }
+    }
+
+    Ok( inwelling )
 }
 
 // the path of the file that stores the downstream crate's manifest directory.
 const MANIFEST_DIR_INWELLING: &'static str = "manifest_dir.inwelling";
 
-fn wait_for_other_builds( build_dir: &Path ) {
+fn wait_for_other_builds( build_dir: &Path ) -> Result<(), InwellingError> {
     let mut generated = HashSet::<PathBuf>::new();
     let mut waiting = true;
     while waiting {
         thread::sleep( Duration::from_secs(5) );
         waiting = false;
         for entry in WalkDir::new( build_dir ) {
-            let entry = entry.unwrap();
+            let entry = entry
+                .map_err( |source| InwellingError::UnwalkableBuildDir{ build_dir: build_dir.to_owned(), source })?;
             let path = entry.path();
             if generated.insert( path.to_owned() ) {
                 waiting = true;
@@ -212,36 +558,237 @@ fn wait_for_other_builds( build_dir: &Path ) {
         }
     }
     eprintln!("{generated:#?}");
+    Ok(())
 }
 
-fn locate_manifest_paths() -> HashMap<PathBuf,Vec<String>> {
+fn locate_manifest_paths() -> Result<HashMap<PathBuf,Vec<String>>, InwellingError> {
     let mut path_bufs = HashMap::new();
 
-    let out_dir = PathBuf::from( env::var( "OUT_DIR" ).expect( "$OUT_DIR should exist." ));
-    let ancestors = out_dir.ancestors();
-    let build_dir = ancestors.skip(2).next().expect( "'build' directory should exist." );
+    let out_dir = PathBuf::from( env::var( "OUT_DIR" ).map_err( |_| InwellingError::MissingOutDir )? );
+    let build_dir = out_dir.ancestors().skip(2).next()
+        .ok_or_else( || InwellingError::MissingBuildDir{ out_dir: out_dir.clone() })?;
 
-    wait_for_other_builds( &build_dir );
+    wait_for_other_builds( build_dir )?;
 
     let mut pending = true;
     while pending {
         pending = false;
-        for entry in build_dir.read_dir().expect( &format!( "to list all sub dirs in {:?}", build_dir )) {
+        let entries = build_dir.read_dir()
+            .map_err( |source| InwellingError::UnreadableBuildDir{ build_dir: build_dir.to_owned(), source })?;
+        for entry in entries {
             if let Ok( entry ) = entry {
                 let path = entry.path();
                 if path.is_dir() {
                     let inwelling_file_path = path.join("out").join( MANIFEST_DIR_INWELLING );
                     if inwelling_file_path.exists() {
                         let contents = fs::read_to_string( &inwelling_file_path )
-                            .expect( &format!( "to read {:?} to get one manifest path", inwelling_file_path ));
+                            .map_err( |source| InwellingError::UnreadableManifestMarker{ path: inwelling_file_path.clone(), source })?;
                         let mut lines = contents.lines();
                         let manifest_dir = lines.next()
-                            .expect( &format!( "{:?} should contain the line of manifest dir.", inwelling_file_path ));
+                            .ok_or_else( || InwellingError::MalformedManifestMarker{ path: inwelling_file_path.clone() })?;
                         path_bufs
                             .entry( PathBuf::from( manifest_dir ).join( "Cargo.toml" ))
                             .or_insert_with( || lines.map( ToOwned::to_owned ).collect() );
     }}}}}
-    path_bufs
+    Ok( path_bufs )
+}
+
+/// Fills in `Package::features` for every already-collected package, keyed by matching
+/// `manifest_path` against `cargo metadata`'s resolve graph (`resolve.nodes[].features`),
+/// so this works regardless of which `Discovery` backend found the packages in the first
+/// place. Reuses `cargo_metadata` if the caller already fetched it (as
+/// `try_collect_downstream_via_cargo_metadata()` does), instead of running `cargo metadata`
+/// a second time for the same build.
+fn dump_features( downstream: &mut Downstream, cargo_metadata: Option<serde_json::Value> ) -> Result<(), InwellingError> {
+    if downstream.packages.is_empty() {
+        return Ok(());
+    }
+
+    let metadata = match cargo_metadata {
+        Some( metadata ) => metadata,
+        None => try_run_cargo_metadata( &[] )?,
+    };
+    fill_features_from_cargo_metadata( downstream, &metadata );
+
+    Ok(())
+}
+
+/// Fills in `Package::features` for every package in `downstream`, keyed by matching
+/// `manifest_path` against `metadata`'s resolve graph (`resolve.nodes[].features`). Split out
+/// from `dump_features()` so the JSON-graph-walking logic can be exercised with a synthetic
+/// `cargo metadata` fixture, without spawning a real `cargo metadata` subprocess.
+fn fill_features_from_cargo_metadata( downstream: &mut Downstream, metadata: &serde_json::Value ) {
+    let packages = metadata["packages"].as_array()
+        .expect( "`cargo metadata`'s packages[] should exist" );
+    let resolve_nodes = metadata["resolve"]["nodes"].as_array()
+        .expect( "`cargo metadata`'s resolve.nodes[] should exist" );
+
+    let id_by_manifest : HashMap<PathBuf, &str> = packages.iter()
+        .map( |package| (
+            PathBuf::from( package["manifest_path"].as_str().expect( "manifest_path should be a string" )),
+            package["id"].as_str().expect( "package id should be a string" ),
+        ))
+        .collect();
+
+    let features_by_id : HashMap<&str, Vec<String>> = resolve_nodes.iter()
+        .map( |node| (
+            node["id"].as_str().expect( "node id should be a string" ),
+            node["features"].as_array()
+                .map( |features| features.iter()
+                    .map( |feature| feature.as_str().expect( "feature should be a string" ).to_owned() )
+                    .collect() )
+                .unwrap_or_default(),
+        ))
+        .collect();
+
+    for package in &mut downstream.packages {
+        if let Some( id ) = id_by_manifest.get( &package.manifest ) {
+            package.features = features_by_id.get( id ).cloned().unwrap_or_default();
+        }
+    }
+}
+
+/// Runs `cargo metadata --format-version 1`, optionally with `extra_args` appended (e.g.
+/// `--no-deps`), and parses its stdout as JSON.
+fn try_run_cargo_metadata( extra_args: &[&str] ) -> Result<serde_json::Value, InwellingError> {
+    let manifest_dir = env::var( "CARGO_MANIFEST_DIR" ).map_err( |_| InwellingError::MissingManifestDir )?;
+    let cargo = env::var( "CARGO" ).unwrap_or_else( |_| "cargo".to_owned() );
+
+    let output = Command::new( &cargo )
+        .args( ["metadata", "--format-version", "1"] )
+        .args( extra_args )
+        .current_dir( &manifest_dir )
+        .output()
+        .map_err( |source| InwellingError::CargoMetadataFailed{ source })?;
+
+    if !output.status.success() {
+        return Err( InwellingError::CargoMetadataExitedWithError{
+            status : output.status,
+            stderr : String::from_utf8_lossy( &output.stderr ).into_owned(),
+        });
+    }
+
+    serde_json::from_slice( &output.stdout )
+        .map_err( |source| InwellingError::MalformedCargoMetadataOutput{ source })
+}
+
+/// Computes the transitive closure of `cargo metadata`'s resolve graph ids that depend
+/// (directly or not) on the package named `build_name`, given that run's `packages[]` and
+/// `resolve.nodes[]`. Split out from `try_collect_downstream_via_cargo_metadata()` so the
+/// graph walk can be exercised with a synthetic `cargo metadata` fixture.
+fn downstream_package_ids<'a>(
+    build_name    : &str,
+    full_packages : &'a [serde_json::Value],
+    resolve_nodes : &'a [serde_json::Value],
+) -> Result<HashSet<&'a str>, InwellingError> {
+    let build_id = full_packages.iter()
+        .find( |package| package["name"].as_str() == Some( build_name ))
+        .map( |package| package["id"].as_str().expect( "package id should be a string" ))
+        .ok_or_else( || InwellingError::PackageNotInCargoMetadata{ name: build_name.to_owned() })?;
+
+    let deps : HashMap<&str, Vec<&str>> = resolve_nodes.iter()
+        .map( |node| (
+            node["id"].as_str().expect( "node id should be a string" ),
+            node["deps"].as_array().expect( "node.deps[] should exist" ).iter()
+                .map( |dep| dep["pkg"].as_str().expect( "dep.pkg should be a string" ))
+                .collect(),
+        ))
+        .collect();
+
+    // transitive closure of "depends (directly or not) on build_id"
+    let mut downstream_ids = HashSet::<&str>::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for ( &id, its_deps ) in &deps {
+            if !downstream_ids.contains( id )
+                && ( its_deps.contains( &build_id ) || its_deps.iter().any( |dep| downstream_ids.contains( dep )))
+            {
+                downstream_ids.insert( id );
+                changed = true;
+            }
+        }
+    }
+
+    Ok( downstream_ids )
+}
+
+/// Discovers downstream crates by walking the `cargo metadata` resolve graph, instead of
+/// polling the `build/*/out/manifest_dir.inwelling` files written by `to()`. The `--no-deps`
+/// run supplies `name`/`manifest_path`/`metadata` for every workspace member; the full run
+/// supplies `resolve.nodes[].deps`, which is walked to find the members that transitively
+/// depend on `build_name`. Also returns the full run's JSON, so callers that also want
+/// `Opts::dump_features` can reuse it instead of invoking `cargo metadata` a second time.
+fn try_collect_downstream_via_cargo_metadata( build_name: &str, Opts{ watch_manifest, watch_rs_files, dump_rs_paths, scan_dirs, include, exclude, .. }: Opts ) -> Result<( Downstream, serde_json::Value ), InwellingError> {
+    let include = try_compile_patterns( &include )?;
+    let exclude = try_compile_patterns( &exclude )?;
+
+    let workspace_metadata = try_run_cargo_metadata( &["--no-deps"] )?;
+    let full_metadata      = try_run_cargo_metadata( &[] )?;
+
+    let workspace_packages = workspace_metadata["packages"].as_array()
+        .expect( "`cargo metadata --no-deps`'s packages[] should exist" );
+    let full_packages = full_metadata["packages"].as_array()
+        .expect( "`cargo metadata`'s packages[] should exist" );
+    let resolve_nodes = full_metadata["resolve"]["nodes"].as_array()
+        .expect( "`cargo metadata`'s resolve.nodes[] should exist" );
+
+    let downstream_ids = downstream_package_ids( build_name, full_packages, resolve_nodes )?;
+
+    let mut inwelling = Downstream::default();
+
+    for package in workspace_packages {
+        let id = package["id"].as_str().expect( "package id should be a string" );
+        if !downstream_ids.contains( id ) {
+            continue;
+        }
+
+        let manifest_path = PathBuf::from(
+            package["manifest_path"].as_str().expect( "manifest_path should be a string" )
+        );
+
+        let package_metadata = package["metadata"]["inwelling"].get( build_name )
+            .map( |metadata_inwelling_build| toml::Value::try_from( metadata_inwelling_build )
+                .map_err( |source| InwellingError::MalformedMetadata{ manifest: manifest_path.clone(), source }))
+            .transpose()?;
+
+        if let Some(( metadata, workspace_metadata )) = resolve_metadata( &manifest_path, build_name, package_metadata ) {
+            let package_name = package["name"].as_str().expect( "package name should be a string" ).to_owned();
+
+            let mut rs_paths = Vec::new();
+
+            if watch_manifest {
+                println!( "cargo:rerun-if-changed={}", manifest_path.to_str().unwrap() );
+            }
+            if dump_rs_paths || watch_rs_files {
+                let manifest_dir = manifest_path.parent().unwrap();
+                rs_paths = scan_filtered_rs_paths( manifest_dir, &scan_dirs, &include, &exclude );
+                if watch_rs_files {
+                    rs_paths.iter().for_each( |rs_file|
+                        println!( "cargo:rerun-if-changed={}", rs_file.to_str().unwrap() ));
+                }
+            }
+
+            inwelling.packages.push( Package{
+                name     : package_name,
+                manifest : manifest_path,
+                metadata,
+                workspace_metadata,
+                rs_paths : if dump_rs_paths { Some( rs_paths ) } else { None },
+                features : Vec::new(),
+            });
+        }
+    }
+
+    if watch_manifest {
+        let workspace_root = PathBuf::from(
+            full_metadata["workspace_root"].as_str().expect( "workspace_root should be a string" )
+        );
+        println!( "cargo:rerun-if-changed={}", workspace_root.join( "Cargo.toml" ).to_str().unwrap() );
+        println!( "cargo:rerun-if-changed={}", workspace_root.join( "Cargo.lock" ).to_str().unwrap() );
+    }
+
+    Ok(( inwelling, full_metadata ))
 }
 
 /// Allow the upstream crate to collect information from this crate.
@@ -268,3 +815,292 @@ pub fn to( upstream: &str ) {
         ).expect( "manifest_dir.txt generated." );
     }
 }
+
+#[cfg( test )]
+mod tests {
+    use super::*;
+
+    fn scratch_dir( name: &str ) -> PathBuf {
+        let dir = env::temp_dir().join( format!( "inwelling-test-{}-{}", name, std::process::id() ));
+        let _ = fs::remove_dir_all( &dir );
+        fs::create_dir_all( &dir ).expect( "to create scratch dir" );
+        dir
+    }
+
+    #[test]
+    fn deep_merge_overlays_and_recurses() {
+        let base = "answer = 42\n[nested]\nkeep = true\nreplace = \"base\"\n"
+            .parse::<toml::Value>().unwrap();
+        let overlay = "extra = \"hi\"\n[nested]\nreplace = \"overlay\"\n"
+            .parse::<toml::Value>().unwrap();
+
+        let merged = deep_merge( base, overlay );
+
+        assert_eq!( merged.get( "answer" ).unwrap().as_integer(), Some( 42 ));
+        assert_eq!( merged.get( "extra" ).unwrap().as_str(), Some( "hi" ));
+        let nested = merged.get( "nested" ).unwrap();
+        assert_eq!( nested.get( "keep" ).unwrap().as_bool(), Some( true ));
+        assert_eq!( nested.get( "replace" ).unwrap().as_str(), Some( "overlay" ));
+    }
+
+    #[test]
+    fn find_workspace_root_walks_up_to_the_workspace_manifest() {
+        let root = scratch_dir( "find-workspace-root" );
+        let member_dir = root.join( "member" );
+        fs::create_dir_all( &member_dir ).unwrap();
+        fs::write( root.join( "Cargo.toml" ), "[workspace]\nmembers = [\"member\"]\n" ).unwrap();
+        fs::write( member_dir.join( "Cargo.toml" ), "[package]\nname = \"member\"\n" ).unwrap();
+
+        let found = find_workspace_root( &member_dir.join( "Cargo.toml" ));
+
+        assert_eq!( found, Some( root.join( "Cargo.toml" )));
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    #[test]
+    fn find_workspace_root_is_none_outside_a_workspace() {
+        let root = scratch_dir( "find-workspace-root-none" );
+        fs::write( root.join( "Cargo.toml" ), "[package]\nname = \"standalone\"\n" ).unwrap();
+
+        assert_eq!( find_workspace_root( &root.join( "Cargo.toml" )), None );
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    #[test]
+    fn resolve_metadata_falls_back_to_workspace_only_metadata() {
+        let root = scratch_dir( "resolve-metadata-workspace-only" );
+        let member_dir = root.join( "member" );
+        fs::create_dir_all( &member_dir ).unwrap();
+        fs::write( root.join( "Cargo.toml" ),
+            "[workspace]\nmembers = [\"member\"]\n[workspace.metadata.inwelling.foo]\nanswer = 42\n" ).unwrap();
+        let member_manifest = member_dir.join( "Cargo.toml" );
+        fs::write( &member_manifest, "[package]\nname = \"member\"\n" ).unwrap();
+
+        let ( metadata, workspace_metadata ) = resolve_metadata( &member_manifest, "foo", None )
+            .expect( "a package relying solely on workspace metadata should still resolve" );
+
+        assert_eq!( metadata.get( "answer" ).unwrap().as_integer(), Some( 42 ));
+        assert_eq!( workspace_metadata.unwrap().get( "answer" ).unwrap().as_integer(), Some( 42 ));
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    #[test]
+    fn resolve_metadata_is_none_without_package_or_workspace_metadata() {
+        let root = scratch_dir( "resolve-metadata-none" );
+        let member_manifest = root.join( "Cargo.toml" );
+        fs::write( &member_manifest, "[package]\nname = \"standalone\"\n" ).unwrap();
+
+        assert_eq!( resolve_metadata( &member_manifest, "foo", None ), None );
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    #[test]
+    fn compile_patterns_compiles_every_glob() {
+        let patterns = try_compile_patterns( &[ "src/**/*.rs".to_owned(), "!tests/*".to_owned() ]).unwrap();
+
+        assert_eq!( patterns.len(), 2 );
+        assert!( patterns[0].matches( "src/foo/bar.rs" ));
+    }
+
+    #[test]
+    fn compile_patterns_fails_on_an_invalid_glob() {
+        let error = try_compile_patterns( &[ "[".to_owned() ]).unwrap_err();
+
+        assert!( matches!( error, InwellingError::InvalidGlobPattern{ pattern, .. } if pattern == "[" ));
+    }
+
+    #[test]
+    fn scan_filtered_rs_paths_honors_include_and_exclude() {
+        let root = scratch_dir( "scan-filtered-rs-paths" );
+        fs::create_dir_all( root.join( "src" )).unwrap();
+        fs::write( root.join( "src" ).join( "lib.rs" ), "" ).unwrap();
+        fs::write( root.join( "src" ).join( "skip.rs" ), "" ).unwrap();
+        fs::write( root.join( "src" ).join( "readme.md" ), "" ).unwrap();
+
+        let include = try_compile_patterns( &[ "src/*.rs".to_owned() ]).unwrap();
+        let exclude = try_compile_patterns( &[ "src/skip.rs".to_owned() ]).unwrap();
+
+        let mut rs_paths = scan_filtered_rs_paths( &root, &[ PathBuf::from( "src" )], &include, &exclude );
+        rs_paths.sort();
+
+        assert_eq!( rs_paths, vec![ root.join( "src" ).join( "lib.rs" )]);
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    #[test]
+    fn scan_filtered_rs_paths_includes_everything_when_include_is_empty() {
+        let root = scratch_dir( "scan-filtered-rs-paths-no-include" );
+        fs::create_dir_all( root.join( "src" )).unwrap();
+        fs::write( root.join( "src" ).join( "lib.rs" ), "" ).unwrap();
+
+        let rs_paths = scan_filtered_rs_paths( &root, &[ PathBuf::from( "src" )], &[], &[] );
+
+        assert_eq!( rs_paths, vec![ root.join( "src" ).join( "lib.rs" )]);
+
+        fs::remove_dir_all( &root ).ok();
+    }
+
+    fn package_with_metadata( name: &str, metadata: &str ) -> Package {
+        Package{
+            name     : name.to_owned(),
+            manifest : PathBuf::from( format!( "{name}/Cargo.toml" )),
+            metadata : metadata.parse::<toml::Value>().unwrap(),
+            workspace_metadata : None,
+            rs_paths : None,
+            features : Vec::new(),
+        }
+    }
+
+    #[derive( serde::Deserialize, Debug, PartialEq )]
+    struct Answer {
+        answer : u32,
+    }
+
+    #[test]
+    fn deserialize_metadata_succeeds_on_matching_shape() {
+        let package = package_with_metadata( "foo", "answer = 42\n" );
+
+        assert_eq!( package.deserialize_metadata::<Answer>().unwrap(), Answer{ answer: 42 });
+    }
+
+    #[test]
+    fn deserialize_metadata_fails_on_mismatched_shape() {
+        let package = package_with_metadata( "foo", "wrong_field = 42\n" );
+
+        let error = package.deserialize_metadata::<Answer>().unwrap_err();
+
+        assert_eq!( error.manifest, PathBuf::from( "foo/Cargo.toml" ));
+    }
+
+    #[test]
+    fn deserialize_all_keys_results_by_package_name() {
+        let downstream = Downstream{
+            packages : vec![
+                package_with_metadata( "foo", "answer = 1\n" ),
+                package_with_metadata( "bar", "answer = 2\n" ),
+            ],
+        };
+
+        let all = downstream.deserialize_all::<Answer>().unwrap();
+
+        assert_eq!( all, vec![
+            ( "foo".to_owned(), Answer{ answer: 1 }),
+            ( "bar".to_owned(), Answer{ answer: 2 }),
+        ]);
+    }
+
+    #[test]
+    fn downstream_package_ids_follows_transitive_deps() {
+        let packages = serde_json::json!([
+            { "id": "foo 0.1.0", "name": "foo" },
+            { "id": "bar 0.1.0", "name": "bar" },
+            { "id": "baz 0.1.0", "name": "baz" },
+            { "id": "unrelated 0.1.0", "name": "unrelated" },
+        ]);
+        let resolve_nodes = serde_json::json!([
+            { "id": "foo 0.1.0", "deps": [] },
+            { "id": "bar 0.1.0", "deps": [ { "pkg": "foo 0.1.0" } ] },
+            { "id": "baz 0.1.0", "deps": [ { "pkg": "bar 0.1.0" } ] },
+            { "id": "unrelated 0.1.0", "deps": [] },
+        ]);
+
+        let ids = downstream_package_ids(
+            "foo",
+            packages.as_array().unwrap(),
+            resolve_nodes.as_array().unwrap(),
+        ).unwrap();
+
+        assert_eq!( ids, HashSet::from([ "bar 0.1.0", "baz 0.1.0" ]) );
+    }
+
+    #[test]
+    fn downstream_package_ids_fails_when_build_name_is_absent() {
+        let packages = serde_json::json!([ { "id": "foo 0.1.0", "name": "foo" } ]);
+        let resolve_nodes = serde_json::json!([ { "id": "foo 0.1.0", "deps": [] } ]);
+
+        let error = downstream_package_ids(
+            "missing",
+            packages.as_array().unwrap(),
+            resolve_nodes.as_array().unwrap(),
+        ).unwrap_err();
+
+        assert!( matches!( error, InwellingError::PackageNotInCargoMetadata{ name } if name == "missing" ));
+    }
+
+    #[test]
+    fn fill_features_from_cargo_metadata_matches_by_manifest_path() {
+        let mut downstream = Downstream{
+            packages : vec![
+                Package{
+                    name     : "foo".to_owned(),
+                    manifest : PathBuf::from( "/ws/foo/Cargo.toml" ),
+                    metadata : toml::Value::Table( toml::Table::new() ),
+                    workspace_metadata : None,
+                    rs_paths : None,
+                    features : Vec::new(),
+                },
+            ],
+        };
+        let metadata = serde_json::json!({
+            "packages": [
+                { "id": "foo 0.1.0", "manifest_path": "/ws/foo/Cargo.toml" },
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "foo 0.1.0", "features": [ "default", "extra" ] },
+                ],
+            },
+        });
+
+        fill_features_from_cargo_metadata( &mut downstream, &metadata );
+
+        assert_eq!( downstream.packages[0].features, vec![ "default".to_owned(), "extra".to_owned() ]);
+    }
+
+    #[test]
+    fn fill_features_from_cargo_metadata_leaves_unmatched_packages_empty() {
+        let mut downstream = Downstream{
+            packages : vec![
+                Package{
+                    name     : "foo".to_owned(),
+                    manifest : PathBuf::from( "/ws/foo/Cargo.toml" ),
+                    metadata : toml::Value::Table( toml::Table::new() ),
+                    workspace_metadata : None,
+                    rs_paths : None,
+                    features : Vec::new(),
+                },
+            ],
+        };
+        let metadata = serde_json::json!({ "packages": [], "resolve": { "nodes": [] } });
+
+        fill_features_from_cargo_metadata( &mut downstream, &metadata );
+
+        assert!( downstream.packages[0].features.is_empty() );
+    }
+
+    #[test]
+    fn try_run_cargo_metadata_surfaces_a_non_zero_exit_status() {
+        let root = scratch_dir( "try-run-cargo-metadata-failure" );
+
+        unsafe {
+            env::set_var( "CARGO_MANIFEST_DIR", &root );
+            env::set_var( "CARGO", "/bin/false" );
+        }
+
+        let error = try_run_cargo_metadata( &[] ).unwrap_err();
+
+        unsafe {
+            env::remove_var( "CARGO" );
+            env::remove_var( "CARGO_MANIFEST_DIR" );
+        }
+
+        assert!( matches!( error, InwellingError::CargoMetadataExitedWithError{ .. } ));
+
+        fs::remove_dir_all( &root ).ok();
+    }
+}